@@ -0,0 +1,422 @@
+//! A pure Rust tree-walking interpreter for `MirModule`. This runs MIR
+//! directly, without going through Cranelift, so callers can fold constants
+//! or use a function's expected result as a test oracle without needing a
+//! working codegen backend.
+
+use crate::{MirFunction, MirInstruction, MirModule, MirOperand};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use thiserror::Error;
+
+/// Default cap on executed instructions used when a caller doesn't specify
+/// one (or passes `0`), so a malformed or infinite MIR loop aborts instead of
+/// hanging the interpreter.
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum InterpError {
+    #[error("Invalid MIR JSON: {0}")]
+    InvalidJson(String),
+    #[error("no such function: {0}")]
+    NoSuchFunction(String),
+    #[error("use of undefined value %{0}")]
+    UndefinedValue(u32),
+    #[error("jump to undefined block: {0}")]
+    UndefinedBlock(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("unsupported instruction: {0}")]
+    UnsupportedInstruction(String),
+    #[error("unsupported operand kind: {0}")]
+    UnsupportedOperand(String),
+    #[error("trap reached")]
+    Trap,
+    #[error("step limit ({0}) exceeded, possible infinite loop")]
+    StepLimitExceeded(u64),
+    #[error("type error: {0}")]
+    TypeError(String),
+}
+
+/// An interpreter-level SSA value. Mirrors the subset of Omni types the
+/// interpreter understands; `Ptr` is an opaque integer since the interpreter
+/// has no real memory to point into.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    I32(i32),
+    F64(f64),
+    Bool(bool),
+    Ptr(u64),
+}
+
+impl Value {
+    fn as_i64(self) -> i64 {
+        match self {
+            Value::I32(v) => v as i64,
+            Value::F64(v) => v as i64,
+            Value::Bool(v) => v as i64,
+            Value::Ptr(v) => v as i64,
+        }
+    }
+
+    fn is_truthy(self) -> bool {
+        match self {
+            Value::I32(v) => v != 0,
+            Value::F64(v) => v != 0.0,
+            Value::Bool(v) => v,
+            Value::Ptr(v) => v != 0,
+        }
+    }
+}
+
+/// Evaluates `entry_name` within the MIR module encoded in `mir_json` and
+/// returns its result, aborting with `StepLimitExceeded` after `max_steps`
+/// executed instructions (`0` means "use [`DEFAULT_MAX_STEPS`]").
+pub fn eval(mir_json: &str, entry_name: &str, max_steps: u64) -> Result<Value, InterpError> {
+    let module: MirModule =
+        serde_json::from_str(mir_json).map_err(|e| InterpError::InvalidJson(e.to_string()))?;
+
+    let func = module
+        .functions
+        .iter()
+        .find(|f| f.name == entry_name)
+        .ok_or_else(|| InterpError::NoSuchFunction(entry_name.to_string()))?;
+
+    if !func.params.is_empty() {
+        return Err(InterpError::TypeError(
+            "eval entry functions with parameters are not yet supported".to_string(),
+        ));
+    }
+
+    let max_steps = if max_steps == 0 { DEFAULT_MAX_STEPS } else { max_steps };
+    run_function(func, max_steps)
+}
+
+fn run_function(func: &MirFunction, max_steps: u64) -> Result<Value, InterpError> {
+    let block_index: HashMap<&str, usize> = func
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.name.as_str(), i))
+        .collect();
+
+    let mut env: HashMap<u32, Value> = HashMap::new();
+    let mut steps: u64 = 0;
+    let mut block_idx = 0usize;
+
+    loop {
+        let block = func
+            .blocks
+            .get(block_idx)
+            .ok_or_else(|| InterpError::UndefinedBlock(format!("#{}", block_idx)))?;
+
+        for inst in &block.instructions {
+            steps += 1;
+            if steps > max_steps {
+                return Err(InterpError::StepLimitExceeded(max_steps));
+            }
+            let val = step_instruction(inst, &env)?;
+            env.insert(inst.id, val);
+        }
+
+        // Same fallthrough convention as the Cranelift lowering: `brz`/`brnz`
+        // name only the taken target, the untaken arm falls through to the
+        // next block in program order.
+        let fallthrough_idx = block_idx + 1;
+
+        match block.terminator.op.as_str() {
+            "ret" => {
+                return match block.terminator.operands.first() {
+                    Some(operand) => resolve_operand(operand, &env),
+                    None => Ok(Value::I32(0)),
+                };
+            }
+            "br" => {
+                let target = block
+                    .terminator
+                    .operands
+                    .first()
+                    .and_then(|op| op.literal.as_ref())
+                    .ok_or_else(|| InterpError::UndefinedBlock("<missing target>".to_string()))?;
+                block_idx = *block_index
+                    .get(target.as_str())
+                    .ok_or_else(|| InterpError::UndefinedBlock(target.clone()))?;
+            }
+            "brz" | "brnz" => {
+                if block.terminator.operands.len() < 2 {
+                    return Err(InterpError::TypeError(
+                        "conditional branch requires condition and target".to_string(),
+                    ));
+                }
+                let cond = resolve_operand(&block.terminator.operands[0], &env)?;
+                let target = block.terminator.operands[1]
+                    .literal
+                    .as_ref()
+                    .ok_or_else(|| InterpError::UndefinedBlock("<missing target>".to_string()))?;
+                let taken_idx = *block_index
+                    .get(target.as_str())
+                    .ok_or_else(|| InterpError::UndefinedBlock(target.clone()))?;
+
+                let take_branch = if block.terminator.op == "brz" {
+                    !cond.is_truthy()
+                } else {
+                    cond.is_truthy()
+                };
+                block_idx = if take_branch { taken_idx } else { fallthrough_idx };
+            }
+            "trap" => return Err(InterpError::Trap),
+            other => {
+                return Err(InterpError::UnsupportedInstruction(other.to_string()));
+            }
+        }
+    }
+}
+
+fn resolve_operand(operand: &MirOperand, env: &HashMap<u32, Value>) -> Result<Value, InterpError> {
+    match operand.kind.as_str() {
+        "value" => {
+            let id = operand
+                .value
+                .ok_or_else(|| InterpError::TypeError("value operand missing id".to_string()))?;
+            env.get(&id).copied().ok_or(InterpError::UndefinedValue(id))
+        }
+        "literal" => {
+            let literal = operand
+                .literal
+                .as_ref()
+                .ok_or_else(|| InterpError::TypeError("literal operand missing value".to_string()))?;
+            literal_value(literal, &operand.operand_type)
+        }
+        other => Err(InterpError::UnsupportedOperand(other.to_string())),
+    }
+}
+
+fn literal_value(literal: &str, operand_type: &str) -> Result<Value, InterpError> {
+    match operand_type {
+        "float" | "double" => literal
+            .parse::<f64>()
+            .map(Value::F64)
+            .map_err(|_| InterpError::TypeError("invalid float literal".to_string())),
+        "bool" => literal
+            .parse::<bool>()
+            .map(Value::Bool)
+            .map_err(|_| InterpError::TypeError("invalid bool literal".to_string())),
+        "string" | "void*" => literal
+            .parse::<u64>()
+            .map(Value::Ptr)
+            .map_err(|_| InterpError::TypeError("invalid pointer literal".to_string())),
+        ty if ty.starts_with('*') => literal
+            .parse::<u64>()
+            .map(Value::Ptr)
+            .map_err(|_| InterpError::TypeError("invalid pointer literal".to_string())),
+        _ => literal
+            .parse::<i32>()
+            .map(Value::I32)
+            .map_err(|_| InterpError::TypeError("invalid integer literal".to_string())),
+    }
+}
+
+fn step_instruction(inst: &MirInstruction, env: &HashMap<u32, Value>) -> Result<Value, InterpError> {
+    match inst.op.as_str() {
+        "const" => {
+            let operand = inst
+                .operands
+                .first()
+                .ok_or_else(|| InterpError::TypeError("const instruction requires operand".to_string()))?;
+            resolve_operand(operand, env)
+        }
+        "add" | "sub" | "mul" | "div" => {
+            if inst.operands.len() < 2 {
+                return Err(InterpError::TypeError(format!(
+                    "{} instruction requires 2 operands",
+                    inst.op
+                )));
+            }
+            let a = resolve_operand(&inst.operands[0], env)?;
+            let b = resolve_operand(&inst.operands[1], env)?;
+            binop(&inst.op, a, b)
+        }
+        "cast" => {
+            let operand = inst
+                .operands
+                .first()
+                .ok_or_else(|| InterpError::TypeError("cast instruction requires operand".to_string()))?;
+            let src = resolve_operand(operand, env)?;
+            cast_value(src, &inst.inst_type)
+        }
+        other => Err(InterpError::UnsupportedInstruction(other.to_string())),
+    }
+}
+
+fn binop(op: &str, a: Value, b: Value) -> Result<Value, InterpError> {
+    match (a, b) {
+        (Value::F64(a), Value::F64(b)) => match op {
+            "add" => Ok(Value::F64(a + b)),
+            "sub" => Ok(Value::F64(a - b)),
+            "mul" => Ok(Value::F64(a * b)),
+            // Deliberately not a DivisionByZero error, unlike the integer
+            // arm below: IEEE 754 defines `x / 0.0` as +-infinity (or NaN
+            // for 0.0 / 0.0), and the Cranelift `fdiv` this interpreter is
+            // meant to act as an oracle for has the same behavior. Making
+            // this an error here would make `eval` diverge from what the
+            // compiled code actually does.
+            "div" => Ok(Value::F64(a / b)),
+            _ => unreachable!(),
+        },
+        (a, b) => {
+            let a = a.as_i64() as i32;
+            let b = b.as_i64() as i32;
+            match op {
+                "add" => Ok(Value::I32(a.wrapping_add(b))),
+                "sub" => Ok(Value::I32(a.wrapping_sub(b))),
+                "mul" => Ok(Value::I32(a.wrapping_mul(b))),
+                "div" => {
+                    if b == 0 {
+                        Err(InterpError::DivisionByZero)
+                    } else {
+                        Ok(Value::I32(a.wrapping_div(b)))
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+fn cast_value(src: Value, inst_type: &str) -> Result<Value, InterpError> {
+    match inst_type {
+        "float" | "double" => Ok(Value::F64(match src {
+            Value::I32(v) => v as f64,
+            Value::F64(v) => v,
+            Value::Bool(v) => v as i32 as f64,
+            Value::Ptr(v) => v as f64,
+        })),
+        "bool" => Ok(Value::Bool(src.is_truthy())),
+        "int" => Ok(Value::I32(src.as_i64() as i32)),
+        _ => Err(InterpError::TypeError(format!(
+            "unsupported cast target: {}",
+            inst_type
+        ))),
+    }
+}
+
+/// Evaluates `entry_name` from `mir_json` and returns its result truncated
+/// to a C `int`, or a negative error code on failure. `max_steps` caps how
+/// many instructions the interpreter will execute before aborting with a
+/// step-limit error (`0` means "use [`DEFAULT_MAX_STEPS`]").
+///
+/// # Safety
+/// Both `mir_json` and `entry_name` pointers must be valid, null-terminated C strings.
+/// The caller is responsible for ensuring the pointers are valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn omni_clift_eval(
+    mir_json: *const c_char,
+    entry_name: *const c_char,
+    max_steps: u64,
+) -> c_int {
+    if mir_json.is_null() || entry_name.is_null() {
+        return -1;
+    }
+
+    let mir_str = CStr::from_ptr(mir_json);
+    let entry_str = CStr::from_ptr(entry_name);
+
+    let mir_payload = match mir_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return -2,
+    };
+
+    let entry_name_str = match entry_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return -3,
+    };
+
+    match eval(mir_payload, entry_name_str, max_steps) {
+        Ok(value) => value.as_i64() as c_int,
+        Err(e) => {
+            eprintln!("MIR interpreter error: {}", e);
+            -4
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn const_return(literal: &str, ty: &str) -> String {
+        format!(
+            r#"{{
+                "functions": [{{
+                    "name": "main",
+                    "return_type": "{ty}",
+                    "params": [],
+                    "blocks": [{{
+                        "name": "entry",
+                        "instructions": [{{
+                            "id": 0,
+                            "op": "const",
+                            "inst_type": "{ty}",
+                            "operands": [{{"kind": "literal", "literal": "{literal}", "operand_type": "{ty}"}}]
+                        }}],
+                        "terminator": {{
+                            "op": "ret",
+                            "operands": [{{"kind": "value", "value": 0, "operand_type": "{ty}"}}]
+                        }}
+                    }}]
+                }}]
+            }}"#
+        )
+    }
+
+    #[test]
+    fn evaluates_trivial_function() {
+        let result = eval(&const_return("42", "int"), "main", 0).unwrap();
+        assert_eq!(result.as_i64(), 42);
+    }
+
+    #[test]
+    fn step_limit_is_configurable() {
+        // A self-loop with no exit: one instruction per pass, then `br`
+        // straight back to `entry`. With a tiny step budget this must abort
+        // quickly instead of running until DEFAULT_MAX_STEPS.
+        let mir = r#"{
+            "functions": [{
+                "name": "main",
+                "return_type": "int",
+                "params": [],
+                "blocks": [{
+                    "name": "entry",
+                    "instructions": [{
+                        "id": 0,
+                        "op": "const",
+                        "inst_type": "int",
+                        "operands": [{"kind": "literal", "literal": "1", "operand_type": "int"}]
+                    }],
+                    "terminator": {
+                        "op": "br",
+                        "operands": [{"kind": "literal", "literal": "entry", "operand_type": "block"}]
+                    }
+                }]
+            }]
+        }"#;
+
+        match eval(mir, "main", 10) {
+            Err(InterpError::StepLimitExceeded(10)) => {}
+            other => panic!("expected StepLimitExceeded(10), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn float_division_by_zero_matches_ieee754_instead_of_erroring() {
+        let result = eval(&const_return("0", "float"), "main", 0).unwrap();
+        // Sanity check the literal itself parsed as a float before testing division.
+        assert!(matches!(result, Value::F64(_)));
+
+        let div_by_zero = binop("div", Value::F64(1.0), Value::F64(0.0)).unwrap();
+        match div_by_zero {
+            Value::F64(v) => assert!(v.is_infinite()),
+            other => panic!("expected a float, got {:?}", other),
+        }
+    }
+}
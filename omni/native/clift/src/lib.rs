@@ -1,15 +1,19 @@
-use cranelift_codegen::ir::{types::*, AbiParam, Function, InstBuilder, Signature};
+use cranelift_codegen::ir::{types::*, AbiParam, Signature};
 use cranelift_codegen::isa::CallConv;
-use cranelift_codegen::settings::{self, Configurable};
-use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
-use cranelift_module::Module;
-use cranelift_object::{ObjectBuilder, ObjectModule};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module};
 use serde::{Deserialize, Serialize};
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
-use target_lexicon::Triple;
 use thiserror::Error;
 
+mod backend;
+mod interp;
+mod link;
+mod optimize;
+
+use backend::{Backend, CraneliftBackend, EmitObject};
+
 #[derive(Error, Debug)]
 pub enum CompileError {
     #[error("Invalid MIR JSON: {0}")]
@@ -20,14 +24,16 @@ pub enum CompileError {
     CraneliftError(String),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("Linker error: {0}")]
+    LinkError(String),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirModule {
     functions: Vec<MirFunction>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirFunction {
     name: String,
     return_type: String,
@@ -35,35 +41,44 @@ struct MirFunction {
     blocks: Vec<MirBlock>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirParam {
     name: String,
     param_type: String,
     id: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirBlock {
     name: String,
     instructions: Vec<MirInstruction>,
     terminator: MirTerminator,
+    /// Compile-time trip-count hint for a simple counted loop whose back-edge
+    /// targets this same block, emitted by the frontend when it already
+    /// knows the bound (e.g. `for i in 0..N`). The optimizer only unrolls
+    /// self-loops that carry this hint; without it the loop is left as a
+    /// back-edge.
+    #[serde(default)]
+    trip_count: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirInstruction {
     id: u32,
     op: String,
     inst_type: String,
     operands: Vec<MirOperand>,
+    #[serde(default)]
+    callee: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirTerminator {
     op: String,
     operands: Vec<MirOperand>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MirOperand {
     kind: String, // "value" or "literal"
     value: Option<u32>,
@@ -182,145 +197,138 @@ pub unsafe extern "C" fn omni_clift_compile_to_object_with_opt(
     }
 }
 
-fn compile_mir_to_object(mir_json: &str, output_path: &str) -> Result<(), CompileError> {
-    compile_mir_to_object_with_opt(mir_json, output_path, "speed")
+/// Compiles MIR JSON and runs `entry_name` immediately in-process via the
+/// Cranelift JIT, without emitting an object file or invoking a linker.
+///
+/// # Safety
+/// Both `mir_json` and `entry_name` pointers must be valid, null-terminated C strings.
+/// `out_result` must be a valid pointer to a writable `i64`.
+/// The caller is responsible for ensuring the pointers are valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn omni_clift_jit_run(
+    mir_json: *const c_char,
+    entry_name: *const c_char,
+    out_result: *mut i64,
+) -> c_int {
+    if mir_json.is_null() || entry_name.is_null() || out_result.is_null() {
+        return -1;
+    }
+
+    let mir_str = CStr::from_ptr(mir_json);
+    let entry_str = CStr::from_ptr(entry_name);
+
+    let mir_payload = match mir_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return -2,
+    };
+
+    let entry_name_str = match entry_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return -3,
+    };
+
+    match compile_mir_to_jit_and_run(mir_payload, entry_name_str) {
+        Ok(result) => {
+            *out_result = result;
+            0
+        }
+        Err(e) => {
+            eprintln!("JIT execution error: {}", e);
+            -4
+        }
+    }
 }
 
-fn compile_mir_to_object_with_opt(mir_json: &str, output_path: &str, opt_level: &str) -> Result<(), CompileError> {
-    // Parse MIR JSON
+/// Compiles every function in `mir_json` into memory and calls `entry_name`,
+/// returning its result as an `i64`. The entry function must take no
+/// parameters; this is meant for REPL/test-harness style evaluation rather
+/// than full program execution.
+fn compile_mir_to_jit_and_run(mir_json: &str, entry_name: &str) -> Result<i64, CompileError> {
     let mir_module: MirModule =
         serde_json::from_str(mir_json).map_err(|e| CompileError::InvalidJson(e.to_string()))?;
 
-    // Set up Cranelift with optimization level
-    let mut flag_builder = settings::builder();
-    
-    // Set optimization level based on input
-    match opt_level {
-        "none" | "0" | "O0" => {
-            flag_builder.set("opt_level", "none").unwrap();
-        }
-        "speed" | "1" | "O1" => {
-            flag_builder.set("opt_level", "speed").unwrap();
-        }
-        "speed_and_size" | "2" | "O2" => {
-            flag_builder.set("opt_level", "speed_and_size").unwrap();
-        }
-        "best" | "3" | "O3" => {
-            flag_builder.set("opt_level", "best").unwrap();
-        }
-        "size" | "s" | "Os" => {
-            flag_builder.set("opt_level", "size").unwrap();
-        }
-        _ => {
-            flag_builder.set("opt_level", "speed").unwrap(); // Default to speed
-        }
+    let entry_func = mir_module
+        .functions
+        .iter()
+        .find(|f| f.name == entry_name)
+        .ok_or_else(|| CompileError::MirParse(format!("no such function: {}", entry_name)))?;
+
+    if !entry_func.params.is_empty() {
+        return Err(CompileError::MirParse(
+            "JIT entry functions with parameters are not yet supported".to_string(),
+        ));
     }
-    
-    let flags = settings::Flags::new(flag_builder);
-    let isa = cranelift_codegen::isa::lookup(Triple::host())
-        .map_err(|e| CompileError::CraneliftError(e.to_string()))?
-        .finish(flags)
-        .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
 
-    // Create object module
-    let object_builder = ObjectBuilder::new(isa, "omni", cranelift_module::default_libcall_names())
-        .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
-    let mut object_module = ObjectModule::new(object_builder);
+    let isa = backend::cranelift_isa("speed")?;
+    let jit_builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    let jit_module = JITModule::new(jit_builder);
+    let mut backend = CraneliftBackend::new(jit_module);
 
-    // Compile each function
+    // Compile each function, sharing `Backend::compile_function` with the
+    // object-emission path.
     for mir_func in &mir_module.functions {
-        compile_function(&mut object_module, mir_func)?;
+        backend.compile_function(mir_func)?;
     }
 
-    // Generate object file
-    let object_product = object_module.finish();
-    let object_data = object_product
-        .emit()
+    let mut jit_module = backend.into_module();
+    jit_module
+        .finalize_definitions()
         .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
-    std::fs::write(output_path, object_data).map_err(CompileError::IoError)?;
-
-    Ok(())
-}
-
-fn compile_function(
-    object_module: &mut ObjectModule,
-    mir_func: &MirFunction,
-) -> Result<(), CompileError> {
-    // Create Cranelift function
-    let mut sig = Signature::new(CallConv::SystemV);
-
-    // Add parameters
-    for param in &mir_func.params {
-        let param_type = omni_type_to_cranelift(&param.param_type)?;
-        sig.params.push(AbiParam::new(param_type));
-    }
 
-    // Add return type
-    let return_type = omni_type_to_cranelift(&mir_func.return_type)?;
-    sig.returns.push(AbiParam::new(return_type));
-
-    let mut func = Function::with_name_signature(
-        cranelift_codegen::ir::UserFuncName::user(0, 0), // Use index 0 for now
-        sig,
-    );
-
-    // Build function body
-    let mut builder_ctx = FunctionBuilderContext::new();
-    let mut builder = FunctionBuilder::new(&mut func, &mut builder_ctx);
-
-    // Create entry block
-    let entry_block = builder.create_block();
-    builder.append_block_params_for_function_params(entry_block);
-    builder.switch_to_block(entry_block);
-
-    // Compile basic blocks
-    let mut block_map = std::collections::HashMap::new();
-    for (i, mir_block) in mir_func.blocks.iter().enumerate() {
-        let block = if i == 0 {
-            entry_block
+    // Must match the signature `compile_function` already declared for this
+    // entry, or `declare_function` will reject the redeclaration.
+    let mut entry_sig = Signature::new(CallConv::SystemV);
+    entry_sig
+        .returns
+        .push(AbiParam::new(omni_type_to_cranelift(&entry_func.return_type)?));
+    let func_id = jit_module
+        .declare_function(entry_name, Linkage::Export, &entry_sig)
+        .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
+    let code_ptr = jit_module.get_finalized_function(func_id);
+
+    // Safety: `code_ptr` was just finalized by this JIT module for a
+    // zero-argument function; the return-type-driven transmute below picks
+    // the ABI matching the signature `compile_function` emitted for it.
+    let result = unsafe {
+        if omni_type_is_float(&entry_func.return_type) {
+            let entry: extern "C" fn() -> f64 = std::mem::transmute(code_ptr);
+            entry() as i64
+        } else if entry_func.return_type == "bool" {
+            let entry: extern "C" fn() -> i8 = std::mem::transmute(code_ptr);
+            entry() as i64
+        } else if omni_type_to_cranelift(&entry_func.return_type)? == I64 {
+            let entry: extern "C" fn() -> i64 = std::mem::transmute(code_ptr);
+            entry()
         } else {
-            builder.create_block()
-        };
-        block_map.insert(mir_block.name.clone(), block);
-    }
-
-    // Compile instructions for each block
-    for mir_block in &mir_func.blocks {
-        let block = block_map[&mir_block.name];
-        builder.switch_to_block(block);
-
-        // Compile instructions
-        for mir_inst in &mir_block.instructions {
-            compile_instruction(&mut builder, mir_inst)?;
+            let entry: extern "C" fn() -> i32 = std::mem::transmute(code_ptr);
+            entry() as i64
         }
+    };
 
-        // Compile terminator
-        compile_terminator(&mut builder, &mir_block.terminator, &block_map)?;
-    }
+    Ok(result)
+}
 
-    // Finalize function
-    builder.finalize();
+fn compile_mir_to_object(mir_json: &str, output_path: &str) -> Result<(), CompileError> {
+    compile_mir_to_object_with_opt(mir_json, output_path, "speed")
+}
 
-    // Add function to module
-    let func_id = object_module
-        .declare_function(
-            &mir_func.name,
-            cranelift_module::Linkage::Export,
-            &func.signature,
-        )
-        .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
+fn compile_mir_to_object_with_opt(mir_json: &str, output_path: &str, opt_level: &str) -> Result<(), CompileError> {
+    // Parse MIR JSON
+    let mut mir_module: MirModule =
+        serde_json::from_str(mir_json).map_err(|e| CompileError::InvalidJson(e.to_string()))?;
 
-    // Create a context for the function
-    let mut ctx = cranelift_codegen::Context::new();
-    ctx.func = func;
-    object_module
-        .define_function(func_id, &mut ctx)
-        .map_err(|e| CompileError::CraneliftError(e.to_string()))?;
+    // Rewrite the MIR (inlining, loop unrolling, ...) before it ever reaches
+    // Cranelift, to the extent `opt_level` asks for it.
+    optimize::optimize(&mut mir_module, opt_level);
 
-    Ok(())
+    let mut backend = CraneliftBackend::new(backend::cranelift_object_module(opt_level)?);
+    for mir_func in &mir_module.functions {
+        backend.compile_function(mir_func)?;
+    }
+    backend.finish_to_object(output_path)
 }
 
-fn omni_type_to_cranelift(omni_type: &str) -> Result<cranelift_codegen::ir::Type, CompileError> {
+pub(crate) fn omni_type_to_cranelift(omni_type: &str) -> Result<cranelift_codegen::ir::Type, CompileError> {
     match omni_type {
         "int" => Ok(I32),
         "float" | "double" => Ok(F64),
@@ -342,154 +350,91 @@ fn omni_type_to_cranelift(omni_type: &str) -> Result<cranelift_codegen::ir::Type
     }
 }
 
-fn compile_instruction(
-    builder: &mut FunctionBuilder,
-    mir_inst: &MirInstruction,
-) -> Result<(), CompileError> {
-    match mir_inst.op.as_str() {
-        "const" => {
-            if mir_inst.operands.is_empty() {
-                return Err(CompileError::MirParse("const instruction requires operand".to_string()));
-            }
-            
-            let operand = &mir_inst.operands[0];
-            if operand.kind != "literal" {
-                return Err(CompileError::MirParse("const instruction requires literal operand".to_string()));
-            }
-            
-            let literal = operand.literal.as_ref()
-                .ok_or_else(|| CompileError::MirParse("Expected literal value".to_string()))?;
-            
-            match mir_inst.inst_type.as_str() {
-                "int" => {
-                    let value = literal.parse::<i32>()
-                        .map_err(|_| CompileError::MirParse("Invalid integer literal".to_string()))?;
-                    let _val = builder.ins().iconst(I32, value as i64);
-                    // TODO: Store the result for later use
-                }
-                "float" | "double" => {
-                    let value = literal.parse::<f64>()
-                        .map_err(|_| CompileError::MirParse("Invalid float literal".to_string()))?;
-                    let _val = builder.ins().f64const(value);
-                    // TODO: Store the result for later use
-                }
-                "bool" => {
-                    let value = literal.parse::<bool>()
-                        .map_err(|_| CompileError::MirParse("Invalid bool literal".to_string()))?;
-                    let _val = builder.ins().iconst(I8, if value { 1 } else { 0 });
-                    // TODO: Store the result for later use
-                }
-                _ => {
-                    return Err(CompileError::MirParse(format!(
-                        "Unsupported const type: {}",
-                        mir_inst.inst_type
-                    )));
-                }
-            }
-        }
-        "add" => {
-            if mir_inst.operands.len() < 2 {
-                return Err(CompileError::MirParse("add instruction requires 2 operands".to_string()));
-            }
-            
-            // TODO: Implement proper operand handling and value mapping
-            // For now, just create a placeholder
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        "sub" => {
-            if mir_inst.operands.len() < 2 {
-                return Err(CompileError::MirParse("sub instruction requires 2 operands".to_string()));
-            }
-            
-            // TODO: Implement proper operand handling and value mapping
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        "mul" => {
-            if mir_inst.operands.len() < 2 {
-                return Err(CompileError::MirParse("mul instruction requires 2 operands".to_string()));
-            }
-            
-            // TODO: Implement proper operand handling and value mapping
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        "div" => {
-            if mir_inst.operands.len() < 2 {
-                return Err(CompileError::MirParse("div instruction requires 2 operands".to_string()));
-            }
-            
-            // TODO: Implement proper operand handling and value mapping
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        "call" => {
-            if mir_inst.operands.is_empty() {
-                return Err(CompileError::MirParse("call instruction requires operands".to_string()));
-            }
-            
-            // TODO: Implement function calls
-            // For now, just create a placeholder
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        "cast" => {
-            if mir_inst.operands.is_empty() {
-                return Err(CompileError::MirParse("cast instruction requires operand".to_string()));
-            }
-            
-            // TODO: Implement type casting
-            let _val = builder.ins().iconst(I32, 0);
-        }
-        _ => {
-            return Err(CompileError::MirParse(format!(
-                "Unsupported instruction: {}",
-                mir_inst.op
-            )));
-        }
-    }
-    Ok(())
+/// True when the given Omni surface type lowers to a Cranelift float type.
+pub(crate) fn omni_type_is_float(omni_type: &str) -> bool {
+    matches!(omni_type, "float" | "double")
 }
 
-fn compile_terminator(
-    builder: &mut FunctionBuilder,
-    terminator: &MirTerminator,
-    _block_map: &std::collections::HashMap<String, cranelift_codegen::ir::Block>,
-) -> Result<(), CompileError> {
-    match terminator.op.as_str() {
-        "ret" => {
-            if terminator.operands.is_empty() {
-                builder.ins().return_(&[]);
-            } else {
-                // TODO: Handle return values properly
-                // For now, just return without values
-                builder.ins().return_(&[]);
-            }
-        }
-        "br" => {
-            if terminator.operands.is_empty() {
-                return Err(CompileError::MirParse("br terminator requires target block".to_string()));
-            }
-            
-            // TODO: Implement proper branch handling
-            // For now, just create a placeholder
-            builder.ins().jump(cranelift_codegen::ir::Block::from_u32(0), &[]);
-        }
-        "brz" | "brnz" => {
-            if terminator.operands.len() < 2 {
-                return Err(CompileError::MirParse("conditional branch requires condition and target".to_string()));
-            }
-            
-            // TODO: Implement conditional branch handling
-            // For now, just create a placeholder
-            builder.ins().jump(cranelift_codegen::ir::Block::from_u32(0), &[]);
-        }
-        "trap" => {
-            // TODO: Implement trap/abort handling
-            builder.ins().trap(cranelift_codegen::ir::TrapCode::UnreachableCodeReached);
-        }
-        _ => {
-            return Err(CompileError::MirParse(format!(
-                "Unsupported terminator: {}",
-                terminator.op
-            )));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single-block `fn() -> int { return 42; }`. Regression test for the
+    /// missing `seal_block` call: before that fix this panicked unconditionally
+    /// in `FunctionBuilder::finalize`, for every MIR program, not just
+    /// multi-block ones.
+    fn trivial_return_42() -> String {
+        r#"{
+            "functions": [{
+                "name": "main",
+                "return_type": "int",
+                "params": [],
+                "blocks": [{
+                    "name": "entry",
+                    "instructions": [{
+                        "id": 0,
+                        "op": "const",
+                        "inst_type": "int",
+                        "operands": [{"kind": "literal", "literal": "42", "operand_type": "int"}]
+                    }],
+                    "terminator": {
+                        "op": "ret",
+                        "operands": [{"kind": "value", "value": 0, "operand_type": "int"}]
+                    }
+                }]
+            }]
+        }"#
+        .to_string()
+    }
+
+    #[test]
+    fn compiles_trivial_function_to_object() {
+        let output_path = std::env::temp_dir().join(format!("omni_clift_test_{}.o", std::process::id()));
+        let output_path_str = output_path.to_str().unwrap();
+
+        let result = compile_mir_to_object(&trivial_return_42(), output_path_str);
+        let _ = std::fs::remove_file(&output_path);
+
+        result.expect("compiling a trivial single-block function should succeed");
+    }
+
+    #[test]
+    fn jit_runs_trivial_function_and_returns_its_result() {
+        let result = compile_mir_to_jit_and_run(&trivial_return_42(), "main")
+            .expect("JIT execution of a trivial single-block function should succeed");
+        assert_eq!(result, 42);
+    }
+
+    /// `1 << 33` on a 32-bit int: the shift count is masked into
+    /// `[0, bit_width)`, so this should behave like `1 << 1` (`33 & 31 == 1`)
+    /// rather than triggering target-dependent over-shift UB.
+    #[test]
+    fn shift_count_is_masked_to_bit_width() {
+        let mir = r#"{
+            "functions": [{
+                "name": "main",
+                "return_type": "int",
+                "params": [],
+                "blocks": [{
+                    "name": "entry",
+                    "instructions": [
+                        {"id": 0, "op": "const", "inst_type": "int", "operands": [{"kind": "literal", "literal": "1", "operand_type": "int"}]},
+                        {"id": 1, "op": "const", "inst_type": "int", "operands": [{"kind": "literal", "literal": "33", "operand_type": "int"}]},
+                        {"id": 2, "op": "shl", "inst_type": "int", "operands": [
+                            {"kind": "value", "value": 0, "operand_type": "int"},
+                            {"kind": "value", "value": 1, "operand_type": "int"}
+                        ]}
+                    ],
+                    "terminator": {
+                        "op": "ret",
+                        "operands": [{"kind": "value", "value": 2, "operand_type": "int"}]
+                    }
+                }]
+            }]
+        }"#;
+
+        let result = compile_mir_to_jit_and_run(mir, "main")
+            .expect("shl with an over-wide shift count should still compile and run");
+        assert_eq!(result, 2);
     }
-    Ok(())
 }
@@ -0,0 +1,255 @@
+//! Drives the platform linker to turn one or more emitted object files into
+//! a runnable executable or shared library. `compile_mir_to_object` only
+//! gets as far as a `.o`; this is what turns that into something you can
+//! actually run or `dlopen`.
+
+use crate::CompileError;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::process::Command;
+use target_lexicon::Triple;
+
+/// What kind of artifact the linker should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Executable,
+    SharedLibrary,
+}
+
+impl LinkKind {
+    fn from_c_int(kind: c_int) -> Result<Self, CompileError> {
+        match kind {
+            0 => Ok(LinkKind::Executable),
+            1 => Ok(LinkKind::SharedLibrary),
+            other => Err(CompileError::LinkError(format!(
+                "unknown link kind: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Links `objects` into `output_path` as the given `kind` of artifact,
+/// shelling out to the host's native linker with `extra_args` appended
+/// verbatim. When `prefer_lld` is set, LLD is used in place of the default
+/// system linker if it's on `PATH` (`cc -fuse-ld=lld` when `ld.lld` is found
+/// on Unix, `lld-link` on Windows) - useful for cross-target links.
+fn link(
+    objects: &[String],
+    output_path: &str,
+    kind: LinkKind,
+    extra_args: &[String],
+    prefer_lld: bool,
+) -> Result<(), CompileError> {
+    let triple = Triple::host();
+    let (program, mut args) = linker_invocation(&triple, kind, output_path, prefer_lld);
+    args.extend(objects.iter().cloned());
+    args.extend(extra_args.iter().cloned());
+
+    let output = Command::new(&program).args(&args).output().map_err(|e| {
+        CompileError::LinkError(format!("failed to invoke linker `{}`: {}", program, e))
+    })?;
+
+    if !output.status.success() {
+        return Err(CompileError::LinkError(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn linker_invocation(
+    triple: &Triple,
+    kind: LinkKind,
+    output_path: &str,
+    prefer_lld: bool,
+) -> (String, Vec<String>) {
+    let os = triple.operating_system.to_string();
+
+    if os == "windows" {
+        let program = if prefer_lld && command_exists("lld-link") {
+            "lld-link"
+        } else {
+            "link.exe"
+        };
+        let mut args = vec![format!("/OUT:{}", output_path)];
+        if kind == LinkKind::SharedLibrary {
+            args.push("/DLL".to_string());
+        }
+        return (program.to_string(), args);
+    }
+
+    let mut args = vec!["-o".to_string(), output_path.to_string()];
+    if prefer_lld && command_exists("ld.lld") {
+        args.push("-fuse-ld=lld".to_string());
+    }
+    if kind == LinkKind::SharedLibrary {
+        if os.contains("darwin") || os.contains("macos") {
+            args.push("-dynamiclib".to_string());
+        } else {
+            args.push("-shared".to_string());
+        }
+    }
+    ("cc".to_string(), args)
+}
+
+/// Whether a file named exactly `name` exists in some directory on `PATH`
+/// (the executable bit and platform extensions such as `.exe` are not checked).
+fn command_exists(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+        .unwrap_or(false)
+}
+
+/// Links a list of object files into an executable or shared library.
+///
+/// # Safety
+/// `objects` and `extra_args` must each be valid pointers to arrays of
+/// `objects_len`/`extra_args_len` valid, null-terminated C strings.
+/// `output_path` must be a valid, null-terminated C string. All pointers
+/// must remain valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn omni_clift_link(
+    objects: *const *const c_char,
+    objects_len: usize,
+    output_path: *const c_char,
+    kind: c_int,
+    extra_args: *const *const c_char,
+    extra_args_len: usize,
+    prefer_lld: c_int,
+) -> c_int {
+    if objects.is_null() || output_path.is_null() {
+        return -1;
+    }
+
+    let object_paths = match c_str_array(objects, objects_len) {
+        Ok(v) => v,
+        Err(_) => return -2,
+    };
+    let extra_arg_strings = if extra_args.is_null() {
+        Vec::new()
+    } else {
+        match c_str_array(extra_args, extra_args_len) {
+            Ok(v) => v,
+            Err(_) => return -3,
+        }
+    };
+
+    let output_path_str = match CStr::from_ptr(output_path).to_str() {
+        Ok(s) => s,
+        Err(_) => return -4,
+    };
+
+    let link_kind = match LinkKind::from_c_int(kind) {
+        Ok(k) => k,
+        Err(_) => return -5,
+    };
+
+    match link(
+        &object_paths,
+        output_path_str,
+        link_kind,
+        &extra_arg_strings,
+        prefer_lld != 0,
+    ) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Link error: {}", e);
+            -6
+        }
+    }
+}
+
+unsafe fn c_str_array(ptr: *const *const c_char, len: usize) -> Result<Vec<String>, ()> {
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let item = *ptr.add(i);
+        if item.is_null() {
+            return Err(());
+        }
+        out.push(CStr::from_ptr(item).to_str().map_err(|_| ())?.to_string());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn linux_executable_uses_cc_with_only_the_output_flag() {
+        let triple = Triple::from_str("x86_64-unknown-linux-gnu").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::Executable, "out", false);
+        assert_eq!(program, "cc");
+        assert_eq!(args, vec!["-o".to_string(), "out".to_string()]);
+    }
+
+    #[test]
+    fn linux_shared_library_adds_shared_flag() {
+        let triple = Triple::from_str("x86_64-unknown-linux-gnu").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::SharedLibrary, "out.so", false);
+        assert_eq!(program, "cc");
+        assert_eq!(
+            args,
+            vec!["-o".to_string(), "out.so".to_string(), "-shared".to_string()]
+        );
+    }
+
+    #[test]
+    fn macos_shared_library_uses_dynamiclib_not_shared() {
+        let triple = Triple::from_str("x86_64-apple-darwin").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::SharedLibrary, "out.dylib", false);
+        assert_eq!(program, "cc");
+        assert_eq!(
+            args,
+            vec![
+                "-o".to_string(),
+                "out.dylib".to_string(),
+                "-dynamiclib".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_executable_uses_link_exe_with_out_flag() {
+        let triple = Triple::from_str("x86_64-pc-windows-msvc").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::Executable, "out.exe", false);
+        assert_eq!(program, "link.exe");
+        assert_eq!(args, vec!["/OUT:out.exe".to_string()]);
+    }
+
+    #[test]
+    fn windows_shared_library_adds_dll_flag() {
+        let triple = Triple::from_str("x86_64-pc-windows-msvc").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::SharedLibrary, "out.dll", false);
+        assert_eq!(program, "link.exe");
+        assert_eq!(
+            args,
+            vec!["/OUT:out.dll".to_string(), "/DLL".to_string()]
+        );
+    }
+
+    #[test]
+    fn prefer_lld_is_ignored_when_ld_lld_is_not_on_path() {
+        // `PATH` in the test environment isn't guaranteed to contain `ld.lld`.
+        // `linker_invocation` adds `-fuse-ld=lld` only when it is found and
+        // otherwise falls back to the default linker flags, so either argument
+        // list is accepted here; the program is `cc` in both cases.
+        let triple = Triple::from_str("x86_64-unknown-linux-gnu").unwrap();
+        let (program, args) = linker_invocation(&triple, LinkKind::Executable, "out", true);
+        assert_eq!(program, "cc");
+        assert!(args == vec!["-o".to_string(), "out".to_string()]
+            || args
+                == vec![
+                    "-o".to_string(),
+                    "out".to_string(),
+                    "-fuse-ld=lld".to_string()
+                ]);
+    }
+
+    #[test]
+    fn command_exists_is_false_for_a_made_up_binary_name() {
+        assert!(!command_exists("omni-clift-link-test-nonexistent-binary"));
+    }
+}
@@ -0,0 +1,661 @@
+//! Pre-codegen MIR optimization pipeline, driven by the `opt_level` string
+//! accepted by [`crate::omni_clift_compile_to_object_with_opt`].
+//!
+//! Three passes run before `compile_function` ever sees the module:
+//!   - deduplication of repeated identical calls within a self-loop body
+//!     (at every opt level)
+//!   - a budgeted inliner for straight-line (single-block) callees
+//!   - a bounded unroller for self-loops carrying a known `trip_count`
+//!
+//! The inliner and the unroller are guarded against the blowup a naive
+//! implementation hits in practice: a tiny `destroy`-style helper called
+//! once per loop iteration, if inlined into every unrolled copy, re-expands
+//! the whole callee on every iteration and explodes memory. We cap total
+//! expansion instead of expanding unconditionally, and collapse repeated
+//! identical calls within one loop body to a single call. That call is not
+//! moved out of the loop: each unrolled copy still contains it once.
+
+use crate::{MirBlock, MirFunction, MirInstruction, MirModule, MirOperand, MirTerminator};
+use std::collections::HashMap;
+
+struct Budgets {
+    /// Max `callee instruction count * call site count` allowed to inline a
+    /// given callee; 0 disables inlining entirely.
+    inline_budget: usize,
+    /// Max total instructions a single unrolled loop body may expand to.
+    expansion_cap: usize,
+    unroll: bool,
+}
+
+fn budgets_for(opt_level: &str) -> Budgets {
+    match opt_level {
+        "none" | "0" | "O0" | "size" | "s" | "Os" => Budgets {
+            inline_budget: 0,
+            expansion_cap: 0,
+            unroll: false,
+        },
+        "best" | "3" | "O3" => Budgets {
+            inline_budget: 512,
+            expansion_cap: 4096,
+            unroll: true,
+        },
+        _ => Budgets {
+            inline_budget: 64,
+            expansion_cap: 512,
+            unroll: true,
+        },
+    }
+}
+
+pub fn optimize(module: &mut MirModule, opt_level: &str) {
+    let budgets = budgets_for(opt_level);
+    hoist_loop_invariant_calls(module);
+    if budgets.inline_budget > 0 {
+        inline_calls(module, &budgets);
+    }
+    if budgets.unroll {
+        unroll_loops(module, budgets.expansion_cap);
+    }
+}
+
+fn max_id_in_function(func: &MirFunction) -> u32 {
+    let mut max_id = func.params.iter().map(|p| p.id).max().unwrap_or(0);
+    for block in &func.blocks {
+        for inst in &block.instructions {
+            max_id = max_id.max(inst.id);
+        }
+    }
+    max_id
+}
+
+fn is_self_loop(block: &MirBlock) -> bool {
+    matches!(block.terminator.op.as_str(), "brz" | "brnz")
+        && block
+            .terminator
+            .operands
+            .get(1)
+            .and_then(|op| op.literal.as_ref())
+            == Some(&block.name)
+}
+
+// --- Call hoisting -----------------------------------------------------
+
+/// Within each self-loop block, collapse repeated `call`s to the same
+/// callee with identical operands down to a single call, so an unrolled
+/// copy of the loop runs that call once instead of once per duplicate.
+/// The surviving call stays inside the loop body; it is not hoisted into a
+/// preceding block.
+fn hoist_loop_invariant_calls(module: &mut MirModule) {
+    for func in &mut module.functions {
+        for block in &mut func.blocks {
+            if !is_self_loop(block) {
+                continue;
+            }
+
+            let mut seen: HashMap<(String, Vec<(String, Option<u32>, Option<String>)>), u32> =
+                HashMap::new();
+            let mut alias: HashMap<u32, u32> = HashMap::new();
+            let mut kept = Vec::with_capacity(block.instructions.len());
+
+            for mut inst in block.instructions.drain(..) {
+                rewrite_operands(&mut inst.operands, &alias);
+                if inst.op == "call" {
+                    if let Some(callee) = inst.callee.clone() {
+                        let key = (
+                            callee,
+                            inst.operands
+                                .iter()
+                                .map(|op| (op.kind.clone(), op.value, op.literal.clone()))
+                                .collect(),
+                        );
+                        if let Some(&first_id) = seen.get(&key) {
+                            alias.insert(inst.id, first_id);
+                            continue;
+                        }
+                        seen.insert(key, inst.id);
+                    }
+                }
+                kept.push(inst);
+            }
+
+            rewrite_operands(&mut block.terminator.operands, &alias);
+            block.instructions = kept;
+        }
+    }
+}
+
+fn rewrite_operands(operands: &mut [MirOperand], alias: &HashMap<u32, u32>) {
+    for op in operands.iter_mut() {
+        if op.kind == "value" {
+            if let Some(id) = op.value {
+                if let Some(&mapped) = alias.get(&id) {
+                    op.value = Some(mapped);
+                }
+            }
+        }
+    }
+}
+
+// --- Inlining ------------------------------------------------------------
+
+/// Inline `call`s to straight-line (single-block, single-`ret`) callees,
+/// gated by an aggregate budget of `callee instruction count * call sites`.
+/// Callees with more than one block are left as real calls: splicing
+/// multi-block control flow into the caller is a separate, riskier
+/// transform not attempted here.
+fn inline_calls(module: &mut MirModule, budgets: &Budgets) {
+    let callees: HashMap<String, MirFunction> = module
+        .functions
+        .iter()
+        .filter(|f| f.blocks.len() == 1 && f.blocks[0].terminator.op == "ret")
+        .map(|f| (f.name.clone(), f.clone()))
+        .collect();
+
+    let mut call_counts: HashMap<String, usize> = HashMap::new();
+    for func in &module.functions {
+        for block in &func.blocks {
+            for inst in &block.instructions {
+                if inst.op == "call" {
+                    if let Some(callee) = &inst.callee {
+                        *call_counts.entry(callee.clone()).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for func in &mut module.functions {
+        let mut next_id = max_id_in_function(func) + 1;
+        for block in &mut func.blocks {
+            let mut new_instructions = Vec::with_capacity(block.instructions.len());
+            for inst in block.instructions.drain(..) {
+                if inst.op == "call" {
+                    if let Some(callee_name) = inst.callee.as_ref() {
+                        if let Some(callee) = callees.get(callee_name) {
+                            let sites = *call_counts.get(callee_name).unwrap_or(&1);
+                            let cost = callee.blocks[0].instructions.len() * sites;
+                            if cost <= budgets.inline_budget {
+                                inline_call(&mut new_instructions, &inst, callee, &mut next_id);
+                                continue;
+                            }
+                        }
+                    }
+                }
+                new_instructions.push(inst);
+            }
+            block.instructions = new_instructions;
+        }
+    }
+}
+
+fn inline_call(
+    out: &mut Vec<MirInstruction>,
+    call: &MirInstruction,
+    callee: &MirFunction,
+    next_id: &mut u32,
+) {
+    let callee_block = &callee.blocks[0];
+    let param_args: HashMap<u32, &MirOperand> = callee
+        .params
+        .iter()
+        .zip(call.operands.iter())
+        .map(|(p, op)| (p.id, op))
+        .collect();
+
+    // If `ret` returns a callee-body instruction's value directly, reuse
+    // `call.id` as that instruction's id instead of generating a fresh one,
+    // so later references to `call.id` resolve without an extra forwarding
+    // instruction.
+    let ret_operand = callee_block.terminator.operands.first();
+    let direct_return_id = ret_operand.and_then(|op| {
+        if op.kind == "value" {
+            op.value
+        } else {
+            None
+        }
+    });
+    let reuse_target = direct_return_id.filter(|id| !param_args.contains_key(id));
+
+    let mut id_map: HashMap<u32, u32> = HashMap::new();
+    for body_inst in &callee_block.instructions {
+        let fresh_id = if Some(body_inst.id) == reuse_target {
+            call.id
+        } else {
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+        id_map.insert(body_inst.id, fresh_id);
+
+        let operands = body_inst
+            .operands
+            .iter()
+            .map(|op| remap_operand(op, &id_map, &param_args))
+            .collect();
+
+        out.push(MirInstruction {
+            id: fresh_id,
+            op: body_inst.op.clone(),
+            inst_type: body_inst.inst_type.clone(),
+            operands,
+            callee: body_inst.callee.clone(),
+        });
+    }
+
+    if reuse_target.is_none() {
+        if let Some(op) = ret_operand {
+            // `ret` returns a param or literal directly: splice an identity
+            // cast so `call.id` still resolves to the forwarded value.
+            let forwarded = remap_operand(op, &id_map, &param_args);
+            out.push(MirInstruction {
+                id: call.id,
+                op: "cast".to_string(),
+                inst_type: call.inst_type.clone(),
+                operands: vec![forwarded],
+                callee: None,
+            });
+        }
+    }
+}
+
+fn remap_operand(
+    op: &MirOperand,
+    id_map: &HashMap<u32, u32>,
+    param_args: &HashMap<u32, &MirOperand>,
+) -> MirOperand {
+    if op.kind == "value" {
+        if let Some(id) = op.value {
+            if let Some(arg) = param_args.get(&id) {
+                return (*arg).clone();
+            }
+            if let Some(&mapped) = id_map.get(&id) {
+                return MirOperand {
+                    kind: "value".to_string(),
+                    value: Some(mapped),
+                    literal: None,
+                    operand_type: op.operand_type.clone(),
+                };
+            }
+        }
+    }
+    op.clone()
+}
+
+// --- Loop unrolling -------------------------------------------------------
+
+/// Unroll self-loop blocks whose `trip_count` is a known compile-time
+/// constant and whose fully-expanded body stays under `expansion_cap`;
+/// otherwise the loop is left as a back-edge.
+fn unroll_loops(module: &mut MirModule, expansion_cap: usize) {
+    for func in &mut module.functions {
+        unroll_function(func, expansion_cap);
+    }
+}
+
+fn unroll_function(func: &mut MirFunction, expansion_cap: usize) {
+    let next_names: Vec<Option<String>> = (0..func.blocks.len())
+        .map(|i| func.blocks.get(i + 1).map(|b| b.name.clone()))
+        .collect();
+    let mut next_id = max_id_in_function(func) + 1;
+
+    for (i, block) in func.blocks.iter_mut().enumerate() {
+        let Some(trip_count) = block.trip_count else {
+            continue;
+        };
+        if !is_self_loop(block) {
+            continue;
+        }
+        let Some(fallthrough) = next_names[i].clone() else {
+            continue; // no exit block to fall through to; leave the back-edge alone
+        };
+
+        let body_len = block.instructions.len();
+        let total = body_len.saturating_mul(trip_count as usize);
+        if trip_count == 0 || total > expansion_cap {
+            continue;
+        }
+
+        // Carries the latest fresh id for each original id *across*
+        // iterations, not just within one. A loop-carried value (e.g. `acc =
+        // acc + 1`) reads and writes the same original id every iteration;
+        // an operand referencing that id must resolve to whatever the
+        // *previous* iteration (or, on the first iteration, whatever was
+        // live before the loop) produced for it - never to the fresh id
+        // this iteration is about to mint for the same instruction. That's
+        // why operands are remapped before `carry` is updated with this
+        // instruction's own id below, and why `carry` isn't reset between
+        // iterations.
+        let mut unrolled = Vec::with_capacity(total);
+        let mut carry: HashMap<u32, u32> = HashMap::new();
+        for _ in 0..trip_count {
+            for inst in &block.instructions {
+                let operands = inst
+                    .operands
+                    .iter()
+                    .map(|op| remap_value_ids(op, &carry))
+                    .collect();
+
+                let fresh_id = next_id;
+                next_id += 1;
+                carry.insert(inst.id, fresh_id);
+
+                unrolled.push(MirInstruction {
+                    id: fresh_id,
+                    op: inst.op.clone(),
+                    inst_type: inst.inst_type.clone(),
+                    operands,
+                    callee: inst.callee.clone(),
+                });
+            }
+        }
+
+        // Blocks after the loop (and the loop's own pre-unroll terminator,
+        // already discarded below) may still reference the loop's original
+        // instruction ids - e.g. reading the final accumulator value by the
+        // id it had before unrolling. Forward each one to whatever id the
+        // last unrolled iteration actually produced, the same forwarding
+        // idiom `inline_call` uses above.
+        for inst in &block.instructions {
+            if let Some(&final_id) = carry.get(&inst.id) {
+                unrolled.push(MirInstruction {
+                    id: inst.id,
+                    op: "cast".to_string(),
+                    inst_type: inst.inst_type.clone(),
+                    operands: vec![MirOperand {
+                        kind: "value".to_string(),
+                        value: Some(final_id),
+                        literal: None,
+                        operand_type: inst.inst_type.clone(),
+                    }],
+                    callee: None,
+                });
+            }
+        }
+
+        block.instructions = unrolled;
+        block.terminator = MirTerminator {
+            op: "br".to_string(),
+            operands: vec![MirOperand {
+                kind: "literal".to_string(),
+                value: None,
+                literal: Some(fallthrough),
+                operand_type: "block".to_string(),
+            }],
+        };
+        block.trip_count = None;
+    }
+}
+
+fn remap_value_ids(op: &MirOperand, id_map: &HashMap<u32, u32>) -> MirOperand {
+    if op.kind == "value" {
+        if let Some(id) = op.value {
+            if let Some(&mapped) = id_map.get(&id) {
+                return MirOperand {
+                    kind: "value".to_string(),
+                    value: Some(mapped),
+                    literal: None,
+                    operand_type: op.operand_type.clone(),
+                };
+            }
+        }
+    }
+    op.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operand_value(id: u32, ty: &str) -> MirOperand {
+        MirOperand {
+            kind: "value".to_string(),
+            value: Some(id),
+            literal: None,
+            operand_type: ty.to_string(),
+        }
+    }
+
+    fn operand_literal(literal: &str, ty: &str) -> MirOperand {
+        MirOperand {
+            kind: "literal".to_string(),
+            value: None,
+            literal: Some(literal.to_string()),
+            operand_type: ty.to_string(),
+        }
+    }
+
+    fn block_target(name: &str) -> MirOperand {
+        MirOperand {
+            kind: "literal".to_string(),
+            value: None,
+            literal: Some(name.to_string()),
+            operand_type: "block".to_string(),
+        }
+    }
+
+    /// `acc = 0; for _ in 0..5 { acc = acc + 1; } return acc;` - the
+    /// loop-carried-accumulator shape the self-referencing-operand bug
+    /// corrupted. Regression test: unrolling this used to either produce a
+    /// self-referential instruction or leave the `ret` referencing an id the
+    /// unrolled block no longer defines.
+    fn counting_loop_module() -> MirModule {
+        MirModule {
+            functions: vec![MirFunction {
+                name: "main".to_string(),
+                return_type: "int".to_string(),
+                params: vec![],
+                blocks: vec![
+                    MirBlock {
+                        name: "entry".to_string(),
+                        instructions: vec![MirInstruction {
+                            id: 13,
+                            op: "const".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_literal("0", "int")],
+                            callee: None,
+                        }],
+                        terminator: MirTerminator {
+                            op: "br".to_string(),
+                            operands: vec![block_target("loop")],
+                        },
+                        trip_count: None,
+                    },
+                    MirBlock {
+                        name: "loop".to_string(),
+                        instructions: vec![MirInstruction {
+                            id: 13,
+                            op: "add".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_value(13, "int"), operand_literal("1", "int")],
+                            callee: None,
+                        }],
+                        terminator: MirTerminator {
+                            op: "brnz".to_string(),
+                            operands: vec![operand_literal("true", "bool"), block_target("loop")],
+                        },
+                        trip_count: Some(5),
+                    },
+                    MirBlock {
+                        name: "exit".to_string(),
+                        instructions: vec![],
+                        terminator: MirTerminator {
+                            op: "ret".to_string(),
+                            operands: vec![operand_value(13, "int")],
+                        },
+                        trip_count: None,
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn unrolling_a_loop_carried_accumulator_does_not_corrupt_it() {
+        let mut module = counting_loop_module();
+        optimize(&mut module, "best");
+
+        let mir_json = serde_json::to_string(&module).unwrap();
+        let result = crate::interp::eval(&mir_json, "main", 0)
+            .expect("unrolled accumulator loop should still evaluate cleanly");
+        assert!(matches!(result, crate::interp::Value::I32(5)));
+    }
+
+    /// `helper()` called twice with identical operands inside a self-loop
+    /// body - the loop-invariant shape `hoist_loop_invariant_calls` exists to
+    /// collapse, since each unrolled copy would otherwise carry both calls.
+    fn repeated_call_loop_module() -> MirModule {
+        MirModule {
+            functions: vec![MirFunction {
+                name: "main".to_string(),
+                return_type: "int".to_string(),
+                params: vec![],
+                blocks: vec![MirBlock {
+                    name: "loop".to_string(),
+                    instructions: vec![
+                        MirInstruction {
+                            id: 1,
+                            op: "call".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_literal("1", "int")],
+                            callee: Some("helper".to_string()),
+                        },
+                        MirInstruction {
+                            id: 2,
+                            op: "call".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_literal("1", "int")],
+                            callee: Some("helper".to_string()),
+                        },
+                        MirInstruction {
+                            id: 3,
+                            op: "add".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_value(1, "int"), operand_value(2, "int")],
+                            callee: None,
+                        },
+                    ],
+                    terminator: MirTerminator {
+                        op: "brnz".to_string(),
+                        operands: vec![operand_literal("true", "bool"), block_target("loop")],
+                    },
+                    trip_count: Some(3),
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn hoisting_collapses_a_repeated_identical_call_in_a_loop_body() {
+        let mut module = repeated_call_loop_module();
+        hoist_loop_invariant_calls(&mut module);
+
+        let block = &module.functions[0].blocks[0];
+        let call_count = block.instructions.iter().filter(|i| i.op == "call").count();
+        assert_eq!(
+            call_count, 1,
+            "the second identical call should have been deduped away"
+        );
+
+        let add = block
+            .instructions
+            .iter()
+            .find(|i| i.op == "add")
+            .expect("the add instruction survives hoisting");
+        assert_eq!(add.operands[0].value, Some(1));
+        assert_eq!(
+            add.operands[1].value,
+            Some(1),
+            "the reference to the deduped call's id should alias to the surviving call"
+        );
+    }
+
+    /// A trivial single-instruction callee called `call_sites` times, so the
+    /// inliner's aggregate cost is exactly `call_sites`.
+    fn caller_calling_inc(call_sites: usize) -> MirModule {
+        let calls = (0..call_sites)
+            .map(|i| MirInstruction {
+                id: i as u32,
+                op: "call".to_string(),
+                inst_type: "int".to_string(),
+                operands: vec![operand_literal("5", "int")],
+                callee: Some("inc".to_string()),
+            })
+            .collect();
+
+        MirModule {
+            functions: vec![
+                MirFunction {
+                    name: "inc".to_string(),
+                    return_type: "int".to_string(),
+                    params: vec![crate::MirParam {
+                        name: "x".to_string(),
+                        param_type: "int".to_string(),
+                        id: 0,
+                    }],
+                    blocks: vec![MirBlock {
+                        name: "entry".to_string(),
+                        instructions: vec![MirInstruction {
+                            id: 1,
+                            op: "add".to_string(),
+                            inst_type: "int".to_string(),
+                            operands: vec![operand_value(0, "int"), operand_literal("1", "int")],
+                            callee: None,
+                        }],
+                        terminator: MirTerminator {
+                            op: "ret".to_string(),
+                            operands: vec![operand_value(1, "int")],
+                        },
+                        trip_count: None,
+                    }],
+                },
+                MirFunction {
+                    name: "main".to_string(),
+                    return_type: "int".to_string(),
+                    params: vec![],
+                    blocks: vec![MirBlock {
+                        name: "entry".to_string(),
+                        instructions: calls,
+                        terminator: MirTerminator {
+                            op: "ret".to_string(),
+                            operands: vec![operand_literal("0", "int")],
+                        },
+                        trip_count: None,
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn inline_calls_respects_the_aggregate_budget_gate() {
+        let budgets = Budgets {
+            inline_budget: 2,
+            expansion_cap: 0,
+            unroll: false,
+        };
+
+        let mut under_budget = caller_calling_inc(2);
+        inline_calls(&mut under_budget, &budgets);
+        assert!(
+            under_budget.functions[1]
+                .blocks[0]
+                .instructions
+                .iter()
+                .all(|i| i.op != "call"),
+            "cost (1 instruction * 2 sites = 2) is within the budget and should be inlined away"
+        );
+
+        let mut over_budget = caller_calling_inc(3);
+        inline_calls(&mut over_budget, &budgets);
+        assert_eq!(
+            over_budget.functions[1]
+                .blocks[0]
+                .instructions
+                .iter()
+                .filter(|i| i.op == "call")
+                .count(),
+            3,
+            "cost (1 instruction * 3 sites = 3) exceeds the budget and should be left as real calls"
+        );
+    }
+}